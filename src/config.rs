@@ -0,0 +1,205 @@
+use crate::lease::{DhcpRange, DhcpRangeBuilder};
+use crate::pxe_menu::{PxeBootMenu, PxeBootMenuEntry};
+use anyhow::{Context, Error};
+use dhcproto::v4::Architecture;
+use serde::de::Error as SerdeError;
+use serde::{Deserialize, Deserializer};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::time::Duration;
+
+/// Top-level shape of the YAML config file: every `add_responder` call that
+/// used to be hardcoded in `main`, plus lease ranges and server bindings.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    pub dhcp_listen_address: Ipv4Addr,
+    /// The address clients should know this server by (option 54 on leases
+    /// handed out from `ranges`), distinct from `dhcp_listen_address` which
+    /// may be `0.0.0.0` to bind every interface.
+    pub server_address: Ipv4Addr,
+    #[serde(default)]
+    pub responders: Vec<ResponderConfig>,
+    #[serde(default)]
+    pub ranges: Vec<RangeConfig>,
+    pub tftp_root: String,
+    pub http_root: String,
+    /// Optional path to additionally append DHCP transaction events (JSON
+    /// lines) to, on top of the stdout stream.
+    #[serde(default)]
+    pub event_log_file: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResponderConfig {
+    #[serde(default, deserialize_with = "deserialize_architecture")]
+    pub architecture: Option<Architecture>,
+    #[serde(default)]
+    pub user_class: Option<String>,
+    pub redirect_to: Ipv4Addr,
+    pub boot_file: String,
+    /// Optional interactive PXE boot menu (e.g. install / rescue / local
+    /// disk) offered to clients matching this responder.
+    #[serde(default)]
+    pub menu: Option<MenuConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MenuConfig {
+    #[serde(default)]
+    pub prompt: Option<String>,
+    pub timeout_secs: u8,
+    pub entries: Vec<MenuEntryConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MenuEntryConfig {
+    pub server_type: u16,
+    pub description: String,
+    pub boot_file: String,
+}
+
+impl MenuConfig {
+    pub fn build(&self) -> PxeBootMenu {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                PxeBootMenuEntry::new(entry.server_type, entry.description.clone(), entry.boot_file.clone())
+            })
+            .collect();
+        PxeBootMenu::new(entries, self.prompt.clone(), self.timeout_secs)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RangeConfig {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+    pub subnet_mask: Ipv4Addr,
+    pub router: Ipv4Addr,
+    #[serde(default)]
+    pub dns: Vec<Ipv4Addr>,
+    pub lease_duration_secs: u64,
+}
+
+impl Config {
+    /// Reads and parses the YAML config at `path`, failing fast with a
+    /// descriptive error if the file is missing or malformed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, Error> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        serde_yaml::from_str(&raw)
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+}
+
+impl RangeConfig {
+    pub fn build(&self) -> Result<DhcpRange, Error> {
+        DhcpRangeBuilder::new()
+            .start(self.start)
+            .end(self.end)
+            .subnet_mask(self.subnet_mask)
+            .router(self.router)
+            .dns(self.dns.clone())
+            .lease_duration(Duration::from_secs(self.lease_duration_secs))
+            .build()
+    }
+}
+
+/// Maps the human-writable architecture keys allowed in config to the
+/// `Architecture` variants the proxy already knows how to respond to.
+fn architecture_from_key(key: &str) -> Option<Architecture> {
+    match key {
+        "bios-x86" => Some(Architecture::Intelx86PC),
+        // PXE arch type 9 ("EFI x86-64"): what virtually all modern UEFI x64
+        // firmware reports in option 93.
+        "uefi-x64" => Some(Architecture::X86_64),
+        // PXE arch type 7 ("EFI BC", BIOS-compatibility mode): older/rarer,
+        // kept as its own key so "uefi-x64" isn't silently wrong for real
+        // x64 UEFI hardware reporting arch 9.
+        "uefi-x64-bc" => Some(Architecture::BC),
+        _ => None,
+    }
+}
+
+fn deserialize_architecture<'de, D>(deserializer: D) -> Result<Option<Architecture>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: Option<String> = Option::deserialize(deserializer)?;
+    raw.map(|key| {
+        architecture_from_key(&key)
+            .ok_or_else(|| SerdeError::custom(format!("unknown architecture '{key}'")))
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Writes `contents` to a uniquely-named file under the system temp dir
+    /// and returns its path, so `Config::load` tests exercise real file I/O.
+    fn write_temp_config(contents: &str) -> std::path::PathBuf {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "rusted-pxe-test-config-{}-{id}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).expect("write temp config");
+        path
+    }
+
+    #[test]
+    fn load_fails_fast_on_missing_file() {
+        let result = Config::load("/nonexistent/rusted-pxe-config.yaml");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_fails_fast_on_malformed_yaml() {
+        let path = write_temp_config("dhcp_listen_address: [this is not valid yaml");
+        let result = Config::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_fails_fast_on_missing_required_field() {
+        // `server_address` is required but omitted.
+        let path = write_temp_config(
+            "dhcp_listen_address: 0.0.0.0\ntftp_root: ./tftp\nhttp_root: ./http\n",
+        );
+        let result = Config::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_parses_a_well_formed_config() {
+        let path = write_temp_config(
+            "dhcp_listen_address: 0.0.0.0\nserver_address: 10.0.0.5\ntftp_root: ./tftp\nhttp_root: ./http\n",
+        );
+        let result = Config::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn architecture_from_key_maps_known_keys() {
+        assert_eq!(architecture_from_key("bios-x86"), Some(Architecture::Intelx86PC));
+        assert_eq!(architecture_from_key("uefi-x64"), Some(Architecture::X86_64));
+        assert_eq!(architecture_from_key("uefi-x64-bc"), Some(Architecture::BC));
+        assert_eq!(architecture_from_key("made-up"), None);
+    }
+
+    #[test]
+    fn deserialize_architecture_rejects_unknown_key() {
+        let yaml = "architecture: made-up\nredirect_to: 10.0.0.5\nboot_file: x\n";
+        let result: Result<ResponderConfig, _> = serde_yaml::from_str(yaml);
+        assert!(result.is_err());
+    }
+}
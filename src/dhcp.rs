@@ -1,3 +1,7 @@
+use crate::error::DhcpError;
+use crate::events::{DhcpEvent, EventSink};
+use crate::lease::{DhcpRange, LeaseTable};
+use crate::pxe_menu::{self, PxeBootMenu};
 use anyhow::Error;
 use core::str;
 use dhcproto::v4::{Architecture, Flags};
@@ -7,28 +11,92 @@ use dhcproto::v4::{
 use log::{info, trace};
 use std::fmt;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
 
+const LEASE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shortest buffer that could plausibly hold a DHCP message: the fixed
+/// 236-byte BOOTP header plus the 4-byte magic cookie. Anything smaller is
+/// dropped before we even try to hand it to `dhcproto`, and this also
+/// guarantees the cookie slice below is always in bounds.
+const MIN_DHCP_MESSAGE_LEN: usize = 240;
+const MAGIC_COOKIE_OFFSET: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// Validates a raw datagram and decodes it into a [`Message`], returning a
+/// typed [`DhcpError`] instead of panicking on truncated or garbage input.
+fn decode_message(data: &[u8]) -> Result<Message, DhcpError> {
+    if data.len() < MIN_DHCP_MESSAGE_LEN {
+        return Err(DhcpError::InvalidBufferLength {
+            actual: data.len(),
+            minimum: MIN_DHCP_MESSAGE_LEN,
+        });
+    }
+
+    if let Some(cookie) = data.get(MAGIC_COOKIE_OFFSET..MAGIC_COOKIE_OFFSET + MAGIC_COOKIE.len()) {
+        if cookie != MAGIC_COOKIE {
+            return Err(DhcpError::InvalidMagicCookie);
+        }
+    }
+
+    Ok(Message::decode(&mut Decoder::new(data))?)
+}
+
 #[derive(Clone, Debug)]
 pub struct DhcpPxeResponder {
     architecture: Option<Architecture>,
     user_class: Option<String>,
     redirect_to: Ipv4Addr,
     boot_file: String,
+    menu: Option<PxeBootMenu>,
 }
 
 #[derive(Clone, Debug)]
 pub struct DHCPProxyBuilder {
+    listen_address: Ipv4Addr,
+    server_address: Ipv4Addr,
     responders: Vec<DhcpPxeResponder>,
+    ranges: Vec<DhcpRange>,
+    event_log_file: Option<PathBuf>,
 }
 
 impl DHCPProxyBuilder {
     pub fn new() -> DHCPProxyBuilder {
         DHCPProxyBuilder {
+            listen_address: Ipv4Addr::UNSPECIFIED,
+            server_address: Ipv4Addr::UNSPECIFIED,
             responders: Vec::new(),
+            ranges: Vec::new(),
+            event_log_file: None,
         }
     }
 
+    /// Interface address the DHCP and proxyDHCP sockets bind to. Defaults to
+    /// `0.0.0.0` (all interfaces).
+    pub fn listen_address(mut self, listen_address: Ipv4Addr) -> DHCPProxyBuilder {
+        self.listen_address = listen_address;
+        self
+    }
+
+    /// The address clients should know this server by, used as option 54
+    /// (`ServerIdentifier`) on leases handed out from `add_range`. Usually a
+    /// single reachable interface address, distinct from `listen_address`
+    /// (which may be `0.0.0.0` to bind every interface).
+    pub fn server_address(mut self, server_address: Ipv4Addr) -> DHCPProxyBuilder {
+        self.server_address = server_address;
+        self
+    }
+
+    /// In addition to stdout, also append each DHCP transaction event as a
+    /// JSON line to this file.
+    pub fn event_log_file(mut self, event_log_file: PathBuf) -> DHCPProxyBuilder {
+        self.event_log_file = Some(event_log_file);
+        self
+    }
+
     pub fn add_responder(
         mut self,
         architecture: Option<Architecture>,
@@ -41,104 +109,555 @@ impl DHCPProxyBuilder {
             user_class,
             redirect_to,
             boot_file,
+            menu: None,
         });
         self
     }
 
+    /// Gives the most recently added responder an interactive PXE boot menu
+    /// (option 43 sub-options 6/8/9/10) instead of a single forced NBP.
+    pub fn with_menu(mut self, menu: PxeBootMenu) -> DHCPProxyBuilder {
+        if let Some(responder) = self.responders.last_mut() {
+            responder.menu = Some(menu);
+        }
+        self
+    }
+
+    /// Adds a pool of addresses the proxy may lease out to clients, built
+    /// with a [`crate::lease::DhcpRangeBuilder`].
+    pub fn add_range(mut self, range: DhcpRange) -> DHCPProxyBuilder {
+        self.ranges.push(range);
+        self
+    }
+
     pub async fn build(self) -> Result<DHCPProxy, Error> {
-        return Ok(DHCPProxy::new(self.responders).await?);
+        let events = EventSink::spawn(self.event_log_file)?;
+        return Ok(DHCPProxy::new(
+            self.listen_address,
+            self.server_address,
+            self.responders,
+            self.ranges,
+            events,
+        )
+        .await?);
     }
 }
 
 pub struct DHCPProxy {
     socket: UdpSocket,
+    proxy_socket: UdpSocket,
+    server_address: Ipv4Addr,
     responders: Vec<DhcpPxeResponder>,
+    ranges: Vec<DhcpRange>,
+    leases: Arc<Mutex<LeaseTable>>,
+    events: EventSink,
     buf: Vec<u8>,
+    proxy_buf: Vec<u8>,
 }
 
 impl DHCPProxy {
-    pub async fn new(responders: Vec<DhcpPxeResponder>) -> Result<DHCPProxy, Error> {
-        let socket = UdpSocket::bind("0.0.0.0:67").await?;
+    pub async fn new(
+        listen_address: Ipv4Addr,
+        server_address: Ipv4Addr,
+        responders: Vec<DhcpPxeResponder>,
+        ranges: Vec<DhcpRange>,
+        events: EventSink,
+    ) -> Result<DHCPProxy, Error> {
+        let socket = UdpSocket::bind((listen_address, 67)).await?;
         socket.set_broadcast(true).unwrap();
 
+        // PXE-standard proxyDHCP port: clients that already hold an IP from a
+        // separate DHCP server re-send their boot request here directly.
+        let proxy_socket = UdpSocket::bind((listen_address, 4011)).await?;
+
         Ok(DHCPProxy {
             socket,
+            proxy_socket,
+            server_address,
             responders,
+            ranges,
+            leases: Arc::new(Mutex::new(LeaseTable::new())),
+            events,
             buf: vec![0; 1500],
+            proxy_buf: vec![0; 1500],
         })
     }
 
     pub async fn run(self) -> Result<(), Error> {
         let DHCPProxy {
             socket,
+            proxy_socket,
+            server_address,
             responders,
+            ranges,
+            leases,
+            events,
             mut buf,
+            mut proxy_buf,
         } = self;
 
         println!("DHCP Listening on: {}", socket.local_addr()?);
+        println!("PXE proxyDHCP Listening on: {}", proxy_socket.local_addr()?);
+
+        let expiry_leases = leases.clone();
+        let sweep_task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(LEASE_SWEEP_INTERVAL).await;
+                let expired = expiry_leases.lock().unwrap().expire_stale(Instant::now());
+                if expired > 0 {
+                    trace!("Expired {expired} stale DHCP lease(s)");
+                }
+            }
+        });
 
-        loop {
-            let valid_bytes = socket.recv(&mut buf).await?;
-            let data = &buf[..valid_bytes];
-
-            let msg = Message::decode(&mut Decoder::new(&data)).unwrap();
-            let response = DHCPProxy::handle_packet(msg, responders.clone());
-            match response {
-                Some(response) => {
-                    let mut response_buffer: Vec<u8> = Vec::new();
-                    let mut response_encoder = Encoder::new(&mut response_buffer);
-                    response.encode(&mut response_encoder)?;
-                    socket
-                        .send_to(response_buffer.as_slice(), "255.255.255.255:68")
-                        .await?;
+        // `main`'s outer loop rebuilds and re-runs a DHCPProxy on any socket
+        // error, so the sweep task above must not outlive this call or every
+        // restart leaks one more of them, each keeping its own LeaseTable
+        // Arc alive. Run the receive loop in its own scope so we always
+        // abort the sweep task before returning, success or error alike.
+        let result: Result<(), Error> = async move {
+            loop {
+                tokio::select! {
+                    result = socket.recv(&mut buf) => {
+                        let valid_bytes = result?;
+                        let data = &buf[..valid_bytes];
+
+                        let msg = match decode_message(data) {
+                            Ok(msg) => msg,
+                            Err(err) => {
+                                trace!("Dropping malformed DHCP packet: {err}");
+                                continue;
+                            }
+                        };
+                        if let Some(response) =
+                            DHCPProxy::handle_packet(msg, server_address, responders.clone(), &ranges, &leases, &events)
+                        {
+                            let mut response_buffer: Vec<u8> = Vec::new();
+                            response.encode(&mut Encoder::new(&mut response_buffer))?;
+                            socket
+                                .send_to(response_buffer.as_slice(), "255.255.255.255:68")
+                                .await?;
+                        }
+                    }
+                    result = proxy_socket.recv_from(&mut proxy_buf) => {
+                        let (valid_bytes, src) = result?;
+                        let data = &proxy_buf[..valid_bytes];
+
+                        let msg = match decode_message(data) {
+                            Ok(msg) => msg,
+                            Err(err) => {
+                                trace!("Dropping malformed proxyDHCP packet: {err}");
+                                continue;
+                            }
+                        };
+                        if let Some(response) =
+                            DHCPProxy::handle_proxy_dhcp_packet(msg, responders.clone(), &events)
+                        {
+                            let mut response_buffer: Vec<u8> = Vec::new();
+                            response.encode(&mut Encoder::new(&mut response_buffer))?;
+                            proxy_socket.send_to(response_buffer.as_slice(), src).await?;
+                        }
+                    }
                 }
-                _ => {}
             }
         }
+        .await;
+
+        sweep_task.abort();
+        result
     }
 
-    fn handle_packet(message: Message, responders: Vec<DhcpPxeResponder>) -> Option<Message> {
+    fn handle_packet(
+        message: Message,
+        server_address: Ipv4Addr,
+        responders: Vec<DhcpPxeResponder>,
+        ranges: &[DhcpRange],
+        leases: &Arc<Mutex<LeaseTable>>,
+        events: &EventSink,
+    ) -> Option<Message> {
         let options = message.opts();
         let mac_address = message.chaddr();
 
         let opcode = message.opcode();
+        let message_type = options.get(OptionCode::MessageType);
+
+        let message_type = match (opcode, message_type) {
+            (Opcode::BootRequest, Some(DhcpOption::MessageType(message_type))) => *message_type,
+            _ => return None,
+        };
+
+        let mac: [u8; 6] = mac_address.try_into().ok()?;
+
+        match message_type {
+            MessageType::Discover | MessageType::Request => {
+                DHCPProxy::handle_lease_request(
+                    message_type,
+                    message,
+                    server_address,
+                    responders,
+                    ranges,
+                    leases,
+                    events,
+                    mac,
+                )
+            }
+            MessageType::Release | MessageType::Decline => {
+                leases.lock().unwrap().release(&mac);
+                info!(
+                    "DHCP {:?} from {}, lease released",
+                    message_type,
+                    HexSlice::new(mac_address)
+                );
+                events.emit(DHCPProxy::build_event(&message, None, None, None));
+                None
+            }
+            _ => {
+                trace!(
+                    "receieved Non-PXE DHCP Packet from {}",
+                    HexSlice::new(mac_address)
+                );
+                None
+            }
+        }
+    }
+
+    fn handle_lease_request(
+        message_type: MessageType,
+        message: Message,
+        server_address: Ipv4Addr,
+        responders: Vec<DhcpPxeResponder>,
+        ranges: &[DhcpRange],
+        leases: &Arc<Mutex<LeaseTable>>,
+        events: &EventSink,
+        mac: [u8; 6],
+    ) -> Option<Message> {
+        let options = message.opts();
+        let mac_address = message.chaddr();
+
+        let requested_ip = match options.get(OptionCode::RequestedIpAddress) {
+            Some(DhcpOption::RequestedIpAddress(ip)) => Some(*ip),
+            _ => None,
+        };
+
+        let mut response = Message::default();
+        response
+            .set_flags(Flags::default().set_broadcast())
+            .set_chaddr(mac_address)
+            .set_xid(message.xid())
+            .set_opcode(Opcode::BootReply);
+
+        let assignment = ranges.iter().find_map(|range| {
+            let now = Instant::now();
+            let mut table = leases.lock().unwrap();
+            table
+                .pick_address(range, &mac, requested_ip, now)
+                .map(|ip| (range, ip))
+        });
+
+        match (message_type, assignment) {
+            (MessageType::Discover, Some((range, ip))) => {
+                leases.lock().unwrap().offer(mac, ip, Instant::now());
+                DHCPProxy::apply_lease_options(&mut response, range, ip, server_address);
+                response
+                    .opts_mut()
+                    .insert(DhcpOption::MessageType(MessageType::Offer));
+                info!(
+                    "DHCP Offer {} to {}",
+                    ip,
+                    HexSlice::new(mac_address)
+                );
+            }
+            (MessageType::Request, Some((range, ip))) => {
+                let now = Instant::now();
+                // A renewal (RFC 2131 unicast DHCPREQUEST mid-lease) carries
+                // `ciaddr`, not option 50, and has no pending offer to match
+                // against; if `pick_address` handed back the client's own
+                // still-valid lease, trust it regardless of what else is set.
+                let is_renewal = leases.lock().unwrap().lease_for(&mac, now) == Some(ip);
+                let pending = leases.lock().unwrap().pending_for(&mac, now);
+                if !is_renewal
+                    && requested_ip.map_or(true, |requested| requested != ip)
+                    && pending.map_or(true, |pending_ip| pending_ip != ip)
+                {
+                    response
+                        .opts_mut()
+                        .insert(DhcpOption::MessageType(MessageType::Nak));
+                    info!("DHCP Nak to {}, requested address unavailable", HexSlice::new(mac_address));
+                } else {
+                    leases.lock().unwrap().commit(mac, ip, range.lease_duration(), now);
+                    DHCPProxy::apply_lease_options(&mut response, range, ip, server_address);
+                    response
+                        .opts_mut()
+                        .insert(DhcpOption::MessageType(MessageType::Ack));
+                    info!("DHCP Ack {} to {}", ip, HexSlice::new(mac_address));
+                }
+            }
+            (MessageType::Request, None) if ranges.is_empty() => {
+                // No address pool configured; fall through to PXE-only handling below.
+            }
+            (MessageType::Request, None) => {
+                response
+                    .opts_mut()
+                    .insert(DhcpOption::MessageType(MessageType::Nak));
+                info!("DHCP Nak to {}, no address available", HexSlice::new(mac_address));
+            }
+            (_, None) => {}
+        }
+
+        let pxe_response = DHCPProxy::handle_pxe(message_type, &message, responders);
+        // A DHCPNAK must not carry boot-file/vendor-extension options (RFC
+        // 2131 §4.3.2); e.g. an INIT-REBOOT client whose remembered address
+        // collides with another lease should get a bare Nak, not one
+        // decorated with PXE parameters.
+        let is_nak = matches!(
+            response.opts().get(OptionCode::MessageType),
+            Some(DhcpOption::MessageType(MessageType::Nak))
+        );
+        if let Some(pxe_fields) = pxe_response.as_ref().filter(|_| !is_nak) {
+            DHCPProxy::apply_pxe_fields(&mut response, message_type, pxe_fields);
+        }
+
+        let assigned_ip = Some(response.yiaddr()).filter(|ip| !ip.is_unspecified());
+        let pxe_fields = pxe_response.as_ref();
+        events.emit(DHCPProxy::build_event(
+            &message,
+            pxe_fields.map(|fields| fields.redirect_to),
+            pxe_fields.map(|fields| fields.boot_file.clone()),
+            assigned_ip,
+        ));
+
+        if response.opts().get(OptionCode::MessageType).is_none() {
+            return None;
+        }
+
+        Some(response)
+    }
+
+    /// Handles a packet received on the proxyDHCP socket (port 4011). These
+    /// clients already hold a lease from a separate DHCP server, so unlike
+    /// `handle_lease_request` this never touches the `LeaseTable` or address
+    /// assignment — it's PXE responder matching only.
+    fn handle_proxy_dhcp_packet(
+        message: Message,
+        responders: Vec<DhcpPxeResponder>,
+        events: &EventSink,
+    ) -> Option<Message> {
+        let options = message.opts();
+        let mac_address = message.chaddr();
+
+        let opcode = message.opcode();
+        let message_type = options.get(OptionCode::MessageType);
+
+        let message_type = match (opcode, message_type) {
+            (Opcode::BootRequest, Some(DhcpOption::MessageType(message_type)))
+                if matches!(message_type, MessageType::Discover | MessageType::Request) =>
+            {
+                *message_type
+            }
+            _ => {
+                trace!(
+                    "receieved Non-PXE proxyDHCP Packet from {}",
+                    HexSlice::new(mac_address)
+                );
+                return None;
+            }
+        };
+
+        let mut response = Message::default();
+        response
+            .set_flags(Flags::default().set_broadcast())
+            .set_chaddr(mac_address)
+            .set_xid(message.xid())
+            .set_opcode(Opcode::BootReply);
+
+        let pxe_response = DHCPProxy::handle_pxe(message_type, &message, responders);
+        if let Some(pxe_fields) = pxe_response.as_ref() {
+            DHCPProxy::apply_pxe_fields(&mut response, message_type, pxe_fields);
+        }
+
+        events.emit(DHCPProxy::build_event(
+            &message,
+            pxe_response.as_ref().map(|fields| fields.redirect_to),
+            pxe_response.as_ref().map(|fields| fields.boot_file.clone()),
+            None,
+        ));
+
+        if response.opts().get(OptionCode::MessageType).is_none() {
+            return None;
+        }
+
+        Some(response)
+    }
+
+    /// Flattens the fields operators care about for observability into a
+    /// [`DhcpEvent`], regardless of whether the packet matched a responder.
+    fn build_event(
+        message: &Message,
+        redirect_to: Option<Ipv4Addr>,
+        boot_file: Option<String>,
+        assigned_ip: Option<Ipv4Addr>,
+    ) -> DhcpEvent {
+        let options = message.opts();
+
+        let architecture = match options.get(OptionCode::ClientSystemArchitecture) {
+            Some(DhcpOption::ClientSystemArchitecture(arch)) => Some(format!("{arch:?}")),
+            _ => None,
+        };
+        let vendor_class = match options.get(OptionCode::ClassIdentifier) {
+            Some(DhcpOption::ClassIdentifier(class_id)) => {
+                Some(String::from_utf8_lossy(class_id).into_owned())
+            }
+            _ => None,
+        };
+        let user_class = match options.get(OptionCode::UserClass) {
+            Some(DhcpOption::UserClass(class)) => Some(String::from_utf8_lossy(class).into_owned()),
+            _ => None,
+        };
+        let parameter_request_list = match options.get(OptionCode::ParameterRequestList) {
+            Some(DhcpOption::ParameterRequestList(params)) => {
+                params.iter().map(|code| format!("{code:?}")).collect()
+            }
+            _ => Vec::new(),
+        };
+        let message_type = match options.get(OptionCode::MessageType) {
+            Some(DhcpOption::MessageType(message_type)) => format!("{message_type:?}"),
+            _ => String::default(),
+        };
+
+        DhcpEvent {
+            xid: message.xid(),
+            client_mac: HexSlice::new(message.chaddr()).to_string(),
+            message_type,
+            architecture,
+            user_class,
+            vendor_class,
+            parameter_request_list,
+            redirect_to,
+            boot_file,
+            assigned_ip,
+        }
+    }
+
+    fn apply_lease_options(response: &mut Message, range: &DhcpRange, ip: Ipv4Addr, server_address: Ipv4Addr) {
+        response.set_yiaddr(ip);
+        response
+            .opts_mut()
+            .insert(DhcpOption::SubnetMask(range.subnet_mask()));
+        response
+            .opts_mut()
+            .insert(DhcpOption::Router(vec![range.router()]));
+        if !range.dns().is_empty() {
+            response
+                .opts_mut()
+                .insert(DhcpOption::DomainNameServer(range.dns().to_vec()));
+        }
+        response
+            .opts_mut()
+            .insert(DhcpOption::AddressLeaseTime(range.lease_duration().as_secs() as u32));
+        // RFC 2131 requires option 54 to identify this server, not the
+        // gateway, so clients target unicast renewals/releases correctly.
+        response
+            .opts_mut()
+            .insert(DhcpOption::ServerIdentifier(server_address));
+    }
+
+    /// Merges a matched responder's boot fields (siaddr/vendor
+    /// extensions/server id/class id/boot file name) onto `response`, filling
+    /// in the message type and server id only if the lease path hasn't
+    /// already set them.
+    fn apply_pxe_fields(response: &mut Message, message_type: MessageType, pxe_fields: &PxeFields) {
+        response
+            .set_siaddr(pxe_fields.redirect_to)
+            .set_sname(pxe_fields.redirect_to.to_string().as_bytes());
+
+        if response.opts().get(OptionCode::MessageType).is_none() {
+            let reply_type = if message_type == MessageType::Request {
+                MessageType::Ack
+            } else {
+                MessageType::Offer
+            };
+            response
+                .opts_mut()
+                .insert(DhcpOption::MessageType(reply_type));
+        }
+
+        response
+            .opts_mut()
+            .insert(DhcpOption::VendorExtensions(pxe_fields.vendor_options.clone()));
+
+        if response.opts().get(OptionCode::ServerIdentifier).is_none() {
+            response
+                .opts_mut()
+                .insert(DhcpOption::ServerIdentifier(pxe_fields.redirect_to));
+        }
+
+        response
+            .opts_mut()
+            .insert(DhcpOption::ClassIdentifier("PXEClient".as_bytes().to_vec()));
+
+        response
+            .opts_mut()
+            .insert(DhcpOption::BootfileName(pxe_fields.boot_file.as_bytes().to_vec()));
+    }
+
+    /// Matches the PXE boot options carried on a `Discover`/`Request` against
+    /// the configured responders. Returns `None` for non-PXE clients.
+    fn handle_pxe(
+        message_type: MessageType,
+        message: &Message,
+        responders: Vec<DhcpPxeResponder>,
+    ) -> Option<PxeFields> {
+        let options = message.opts();
+        let mac_address = message.chaddr();
+
+        let requested_params = options.get(OptionCode::ParameterRequestList);
+        let vendor_class = options.get(OptionCode::ClassIdentifier);
         let architecture = options.get(OptionCode::ClientSystemArchitecture);
         let network_interface = options.get(OptionCode::ClientNetworkInterface);
-        let vendor_class = options.get(OptionCode::ClassIdentifier);
-        let message_type = options.get(OptionCode::MessageType);
         let user_class = options.get(OptionCode::UserClass);
-        let requested_params = options.get(OptionCode::ParameterRequestList);
 
         match (
-            opcode,
-            message_type,
             requested_params,
             vendor_class,
             architecture,
             network_interface,
         ) {
             (
-                Opcode::BootRequest,                         // is a boot request
-                Some(DhcpOption::MessageType(message_type)), // option 53 is set
-                Some(DhcpOption::ParameterRequestList(_)),   //option 55 is set
+                Some(DhcpOption::ParameterRequestList(_)), //option 55 is set
                 Some(DhcpOption::ClassIdentifier(class_id)), // option 60 is set
                 Some(DhcpOption::ClientSystemArchitecture(request_architecture)), // option 93 is set
                 Some(DhcpOption::ClientNetworkInterface(_, _, _)), // option 94 is set
             ) => {
-                if message_type != &MessageType::Discover && message_type != &MessageType::Request {
-                    // message_type(opt 53) must be Discover or Request
-                    return None;
-                }
-
-                let class_id_str: &str = str::from_utf8(class_id).unwrap_or_default();
+                let class_id_str = match str::from_utf8(class_id).map_err(DhcpError::from) {
+                    Ok(class_id_str) => class_id_str,
+                    Err(err) => {
+                        trace!(
+                            "Dropping DHCP packet from {}: {err}",
+                            HexSlice::new(mac_address)
+                        );
+                        return None;
+                    }
+                };
                 if !class_id_str.starts_with("PXEClient") {
                     // class_id(opt 60) must start with PXEClient
                     return None;
                 }
 
+                // Invalid UTF-8 here only disqualifies responders keyed on
+                // user_class; it shouldn't drop the whole PXE response for
+                // responders matched on architecture alone (or unfiltered).
                 let request_user_class = match user_class {
                     Some(DhcpOption::UserClass(class)) => {
-                        String::from_utf8(class.to_vec()).unwrap_or_default()
+                        match str::from_utf8(class).map_err(DhcpError::from) {
+                            Ok(class) => class.to_string(),
+                            Err(err) => {
+                                trace!(
+                                    "Invalid UTF-8 in UserClass option from {}: {err}",
+                                    HexSlice::new(mac_address)
+                                );
+                                String::default()
+                            }
+                        }
                     }
                     Some(_) => String::default(),
                     None => String::default(),
@@ -153,34 +672,44 @@ impl DHCPProxy {
 
                 let mut redirect_to = Ipv4Addr::new(0, 0, 0, 0);
                 let mut boot_file = String::default();
+                let mut menu: Option<PxeBootMenu> = None;
 
                 for responder in responders {
-                    match (responder.architecture, responder.user_class) {
-                        (None, None) => {
-                            redirect_to = responder.redirect_to;
-                            boot_file = responder.boot_file;
-                        }
-                        (Some(arch), None) => {
-                            if &arch == request_architecture {
-                                redirect_to = responder.redirect_to;
-                                boot_file = responder.boot_file;
-                            }
-                        }
-                        (None, Some(class)) => {
-                            if class == request_user_class {
-                                redirect_to = responder.redirect_to;
-                                boot_file = responder.boot_file;
-                            }
-                        }
+                    let matches = match (responder.architecture, &responder.user_class) {
+                        (None, None) => true,
+                        (Some(arch), None) => &arch == request_architecture,
+                        (None, Some(class)) => class == &request_user_class,
                         (Some(arch), Some(class)) => {
-                            if &arch == request_architecture && class == request_user_class {
-                                redirect_to = responder.redirect_to;
-                                boot_file = responder.boot_file;
-                            }
+                            &arch == request_architecture && class == &request_user_class
                         }
+                    };
+                    if matches {
+                        redirect_to = responder.redirect_to;
+                        boot_file = responder.boot_file;
+                        menu = responder.menu;
                     }
                 }
 
+                // A client that already saw our menu re-sends its pick via
+                // option 43 sub-option 71; honor it if it names a known entry.
+                let selected_boot_file = options
+                    .get(OptionCode::VendorExtensions)
+                    .and_then(|opt| match opt {
+                        DhcpOption::VendorExtensions(bytes) => pxe_menu::selected_boot_item(bytes),
+                        _ => None,
+                    })
+                    .and_then(|server_type| {
+                        menu.as_ref().and_then(|menu| menu.boot_file_for(server_type))
+                    });
+                if let Some(selected_boot_file) = selected_boot_file {
+                    boot_file = selected_boot_file.to_string();
+                }
+
+                let vendor_options = match &menu {
+                    Some(menu) => menu.encode(redirect_to),
+                    None => vec![6, 8, 0, 0, 0, 0, 0, 0, 0, 0, 255],
+                };
+
                 info!(
                     "Responding to {} ({:?},{}) with {} ({})",
                     HexSlice::new(mac_address),
@@ -190,40 +719,11 @@ impl DHCPProxy {
                     boot_file
                 );
 
-                let mut response = Message::default();
-                response
-                    .set_flags(Flags::default().set_broadcast())
-                    .set_chaddr(&mac_address)
-                    .set_xid(message.xid())
-                    .set_siaddr(redirect_to)
-                    .set_sname(redirect_to.to_string().as_bytes())
-                    .set_opcode(Opcode::BootReply)
-                    .opts_mut()
-                    .insert(DhcpOption::MessageType(MessageType::Offer));
-
-                let mut vendor_options: Vec<u8> = Vec::new();
-                vendor_options.push(6);                                     // Set Option 6
-                vendor_options.push(8);                                     // Length 8 Bytes
-                vendor_options.append(&mut vec![0, 0, 0, 0, 0, 0, 0, 0]);   // 8 Empty Bytes
-                vendor_options.push(255);                                   // PXEClient End
-
-                response
-                    .opts_mut()
-                    .insert(DhcpOption::VendorExtensions(vendor_options));
-
-                response
-                    .opts_mut()
-                    .insert(DhcpOption::ServerIdentifier(redirect_to));
-
-                response
-                    .opts_mut()
-                    .insert(DhcpOption::ClassIdentifier("PXEClient".as_bytes().to_vec()));
-
-                response
-                    .opts_mut()
-                    .insert(DhcpOption::BootfileName(boot_file.as_bytes().to_vec()));
-
-                Some(response)
+                Some(PxeFields {
+                    redirect_to,
+                    boot_file,
+                    vendor_options,
+                })
             }
             _ => {
                 trace!(
@@ -236,6 +736,12 @@ impl DHCPProxy {
     }
 }
 
+struct PxeFields {
+    redirect_to: Ipv4Addr,
+    boot_file: String,
+    vendor_options: Vec<u8>,
+}
+
 struct HexSlice<'a>(&'a [u8]);
 
 impl<'a> HexSlice<'a> {
@@ -261,3 +767,33 @@ impl fmt::Display for HexSlice<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_packet() -> Vec<u8> {
+        let mut data = vec![0u8; MAGIC_COOKIE_OFFSET];
+        data.extend_from_slice(&MAGIC_COOKIE);
+        data
+    }
+
+    #[test]
+    fn decode_message_rejects_buffers_shorter_than_the_cookie_offset() {
+        // 232-239 bytes used to slip past the old MIN_DHCP_MESSAGE_LEN (232)
+        // while being too short for the cookie slice at 236..240, silently
+        // skipping the cookie check instead of enforcing it.
+        let data = vec![0u8; 235];
+        assert!(matches!(
+            decode_message(&data),
+            Err(DhcpError::InvalidBufferLength { actual: 235, minimum: 240 })
+        ));
+    }
+
+    #[test]
+    fn decode_message_rejects_missing_magic_cookie() {
+        let mut data = valid_packet();
+        data[MAGIC_COOKIE_OFFSET] = 0;
+        assert!(matches!(decode_message(&data), Err(DhcpError::InvalidMagicCookie)));
+    }
+}
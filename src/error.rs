@@ -0,0 +1,42 @@
+use std::fmt;
+use std::str::Utf8Error;
+
+/// Errors that can occur while turning a raw UDP datagram into a validated
+/// [`dhcproto::v4::Message`]. Keeping these typed (instead of `unwrap`ing the
+/// decode step) means a single malformed packet can be logged and dropped
+/// rather than taking down the DHCP task.
+#[derive(Debug)]
+pub enum DhcpError {
+    InvalidBufferLength { actual: usize, minimum: usize },
+    InvalidMagicCookie,
+    Decode(dhcproto::error::DecodeError),
+    InvalidUtf8(Utf8Error),
+}
+
+impl fmt::Display for DhcpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhcpError::InvalidBufferLength { actual, minimum } => write!(
+                f,
+                "packet too short to be a DHCP message ({actual} < {minimum} bytes)"
+            ),
+            DhcpError::InvalidMagicCookie => write!(f, "missing or invalid BOOTP magic cookie"),
+            DhcpError::Decode(err) => write!(f, "failed to decode DHCP message: {err}"),
+            DhcpError::InvalidUtf8(err) => write!(f, "invalid UTF-8 in DHCP option: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DhcpError {}
+
+impl From<dhcproto::error::DecodeError> for DhcpError {
+    fn from(err: dhcproto::error::DecodeError) -> DhcpError {
+        DhcpError::Decode(err)
+    }
+}
+
+impl From<Utf8Error> for DhcpError {
+    fn from(err: Utf8Error) -> DhcpError {
+        DhcpError::InvalidUtf8(err)
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Error;
+use log::error;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// A single handled DHCP/PXE transaction, flattened to the fields operators
+/// actually want to grep/correlate on instead of the free-form log lines.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DhcpEvent {
+    pub xid: u32,
+    pub client_mac: String,
+    pub message_type: String,
+    pub architecture: Option<String>,
+    pub user_class: Option<String>,
+    pub vendor_class: Option<String>,
+    pub parameter_request_list: Vec<String>,
+    pub redirect_to: Option<Ipv4Addr>,
+    pub boot_file: Option<String>,
+    pub assigned_ip: Option<Ipv4Addr>,
+}
+
+/// Fans handled-packet events out to stdout and, optionally, a file, off the hot path:
+/// `emit` just pushes onto a channel, a background task does the formatting
+/// and I/O.
+#[derive(Clone)]
+pub struct EventSink {
+    sender: UnboundedSender<DhcpEvent>,
+}
+
+impl EventSink {
+    /// Spawns the background writer task and returns a handle to send events to it.
+    pub fn spawn(file_path: Option<PathBuf>) -> Result<EventSink, Error> {
+        let mut file = file_path
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+            .transpose()?;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<DhcpEvent>();
+
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                let line = match serde_json::to_string(&event) {
+                    Ok(line) => line,
+                    Err(err) => {
+                        error!("Failed to serialize DHCP event: {err}");
+                        continue;
+                    }
+                };
+
+                println!("{line}");
+
+                if let Some(file) = file.as_mut() {
+                    if let Err(err) = writeln!(file, "{line}") {
+                        error!("Failed to write DHCP event to file: {err}");
+                    }
+                }
+            }
+        });
+
+        Ok(EventSink { sender })
+    }
+
+    pub fn emit(&self, event: DhcpEvent) {
+        // The receiver only goes away if the writer task panicked; dropping
+        // the event in that case beats taking down the DHCP loop over it.
+        let _ = self.sender.send(event);
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// A committed IP assignment for a single client, keyed by MAC address.
+#[derive(Clone, Copy, Debug)]
+pub struct Lease {
+    pub ip: Ipv4Addr,
+    pub expires_at: Instant,
+}
+
+impl Lease {
+    fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// A contiguous pool of addresses the proxy is allowed to hand out, along with
+/// the options that go with it (mask/router/dns/lease time).
+#[derive(Clone, Debug)]
+pub struct DhcpRange {
+    start: Ipv4Addr,
+    end: Ipv4Addr,
+    subnet_mask: Ipv4Addr,
+    router: Ipv4Addr,
+    dns: Vec<Ipv4Addr>,
+    lease_duration: Duration,
+}
+
+impl DhcpRange {
+    pub fn subnet_mask(&self) -> Ipv4Addr {
+        self.subnet_mask
+    }
+
+    pub fn router(&self) -> Ipv4Addr {
+        self.router
+    }
+
+    pub fn dns(&self) -> &[Ipv4Addr] {
+        &self.dns
+    }
+
+    pub fn lease_duration(&self) -> Duration {
+        self.lease_duration
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        u32::from(ip) >= u32::from(self.start) && u32::from(ip) <= u32::from(self.end)
+    }
+
+    fn addrs(&self) -> impl Iterator<Item = Ipv4Addr> {
+        (u32::from(self.start)..=u32::from(self.end)).map(Ipv4Addr::from)
+    }
+}
+
+/// Builds a [`DhcpRange`] field-by-field, mirroring the `DHCPProxyBuilder`
+/// chain-call style used for responders.
+#[derive(Clone, Debug, Default)]
+pub struct DhcpRangeBuilder {
+    start: Option<Ipv4Addr>,
+    end: Option<Ipv4Addr>,
+    subnet_mask: Option<Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_duration: Option<Duration>,
+}
+
+impl DhcpRangeBuilder {
+    pub fn new() -> DhcpRangeBuilder {
+        DhcpRangeBuilder::default()
+    }
+
+    pub fn start(mut self, start: Ipv4Addr) -> DhcpRangeBuilder {
+        self.start = Some(start);
+        self
+    }
+
+    pub fn end(mut self, end: Ipv4Addr) -> DhcpRangeBuilder {
+        self.end = Some(end);
+        self
+    }
+
+    pub fn subnet_mask(mut self, subnet_mask: Ipv4Addr) -> DhcpRangeBuilder {
+        self.subnet_mask = Some(subnet_mask);
+        self
+    }
+
+    pub fn router(mut self, router: Ipv4Addr) -> DhcpRangeBuilder {
+        self.router = Some(router);
+        self
+    }
+
+    pub fn dns(mut self, dns: Vec<Ipv4Addr>) -> DhcpRangeBuilder {
+        self.dns = dns;
+        self
+    }
+
+    pub fn lease_duration(mut self, lease_duration: Duration) -> DhcpRangeBuilder {
+        self.lease_duration = Some(lease_duration);
+        self
+    }
+
+    pub fn build(self) -> Result<DhcpRange, Error> {
+        let start = self.start.ok_or_else(|| anyhow!("DHCP range is missing a start address"))?;
+        let end = self.end.ok_or_else(|| anyhow!("DHCP range is missing an end address"))?;
+        if u32::from(start) > u32::from(end) {
+            return Err(anyhow!("DHCP range start {start} is after end {end}"));
+        }
+
+        Ok(DhcpRange {
+            start,
+            end,
+            subnet_mask: self
+                .subnet_mask
+                .ok_or_else(|| anyhow!("DHCP range is missing a subnet mask"))?,
+            router: self.router.ok_or_else(|| anyhow!("DHCP range is missing a router"))?,
+            dns: self.dns,
+            lease_duration: self
+                .lease_duration
+                .ok_or_else(|| anyhow!("DHCP range is missing a lease duration"))?,
+        })
+    }
+}
+
+/// How long an offered-but-uncommitted address holds its place in the pool
+/// before the background sweep reclaims it. Covers clients that DISCOVER but
+/// never follow up with a REQUEST (roaming clients, port scans, flaky NICs).
+const PENDING_OFFER_TTL: Duration = Duration::from_secs(60);
+
+/// Tracks committed leases and in-flight offers across one or more
+/// [`DhcpRange`]s, keyed by client MAC address.
+#[derive(Debug, Default)]
+pub struct LeaseTable {
+    leases: HashMap<[u8; 6], Lease>,
+    pending: HashMap<[u8; 6], (Ipv4Addr, Instant)>,
+}
+
+impl LeaseTable {
+    pub fn new() -> LeaseTable {
+        LeaseTable::default()
+    }
+
+    /// The client's existing, still-valid lease, if any.
+    pub fn lease_for(&self, mac: &[u8; 6], now: Instant) -> Option<Ipv4Addr> {
+        self.leases
+            .get(mac)
+            .filter(|lease| !lease.is_expired(now))
+            .map(|lease| lease.ip)
+    }
+
+    /// The address we most recently offered this client, awaiting a Request.
+    /// `None` if there is no offer, or it has sat unclaimed past
+    /// [`PENDING_OFFER_TTL`].
+    pub fn pending_for(&self, mac: &[u8; 6], now: Instant) -> Option<Ipv4Addr> {
+        self.pending
+            .get(mac)
+            .filter(|(_, offered_at)| now.saturating_duration_since(*offered_at) < PENDING_OFFER_TTL)
+            .map(|(ip, _)| *ip)
+    }
+
+    fn is_held_by_other(&self, ip: Ipv4Addr, mac: &[u8; 6], now: Instant) -> bool {
+        let leased = self
+            .leases
+            .iter()
+            .any(|(other, lease)| other != mac && lease.ip == ip && !lease.is_expired(now));
+        let offered = self.pending.iter().any(|(other, (pending_ip, offered_at))| {
+            other != mac
+                && *pending_ip == ip
+                && now.saturating_duration_since(*offered_at) < PENDING_OFFER_TTL
+        });
+        leased || offered
+    }
+
+    /// Picks an address for `mac`: its existing in-range lease, else the
+    /// requested address if free, else the next free address in `range`.
+    pub fn pick_address(
+        &self,
+        range: &DhcpRange,
+        mac: &[u8; 6],
+        requested: Option<Ipv4Addr>,
+        now: Instant,
+    ) -> Option<Ipv4Addr> {
+        if let Some(existing) = self.lease_for(mac, now) {
+            if range.contains(existing) {
+                return Some(existing);
+            }
+        }
+
+        if let Some(requested) = requested {
+            if range.contains(requested) && !self.is_held_by_other(requested, mac, now) {
+                return Some(requested);
+            }
+        }
+
+        range
+            .addrs()
+            .find(|ip| !self.is_held_by_other(*ip, mac, now))
+    }
+
+    pub fn offer(&mut self, mac: [u8; 6], ip: Ipv4Addr, now: Instant) {
+        self.pending.insert(mac, (ip, now));
+    }
+
+    /// Commits `ip` as the lease for `mac`, expiring `lease_duration` after
+    /// `now`, and drops any pending offer for `mac`.
+    pub fn commit(&mut self, mac: [u8; 6], ip: Ipv4Addr, lease_duration: Duration, now: Instant) {
+        self.pending.remove(&mac);
+        self.leases.insert(
+            mac,
+            Lease {
+                ip,
+                expires_at: now + lease_duration,
+            },
+        );
+    }
+
+    pub fn release(&mut self, mac: &[u8; 6]) {
+        self.leases.remove(mac);
+        self.pending.remove(mac);
+    }
+
+    /// Drops any committed lease that has expired and any offer that's sat
+    /// unclaimed past [`PENDING_OFFER_TTL`]. Returns how many were removed.
+    pub fn expire_stale(&mut self, now: Instant) -> usize {
+        let before_leases = self.leases.len();
+        self.leases.retain(|_, lease| !lease.is_expired(now));
+
+        let before_pending = self.pending.len();
+        self.pending
+            .retain(|_, (_, offered_at)| now.saturating_duration_since(*offered_at) < PENDING_OFFER_TTL);
+
+        (before_leases - self.leases.len()) + (before_pending - self.pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u8, end: u8) -> DhcpRange {
+        DhcpRangeBuilder::new()
+            .start(Ipv4Addr::new(10, 0, 0, start))
+            .end(Ipv4Addr::new(10, 0, 0, end))
+            .subnet_mask(Ipv4Addr::new(255, 255, 255, 0))
+            .router(Ipv4Addr::new(10, 0, 0, 1))
+            .lease_duration(Duration::from_secs(3600))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn pick_address_renews_existing_lease_with_no_requested_ip() {
+        let now = Instant::now();
+        let range = range(10, 20);
+        let mac = [1, 2, 3, 4, 5, 6];
+
+        let mut table = LeaseTable::new();
+        table.offer(mac, Ipv4Addr::new(10, 0, 0, 10), now);
+        table.commit(mac, Ipv4Addr::new(10, 0, 0, 10), Duration::from_secs(3600), now);
+
+        // A mid-lease renewal carries no option 50 (Requested IP).
+        let picked = table.pick_address(&range, &mac, None, now);
+        assert_eq!(picked, Some(Ipv4Addr::new(10, 0, 0, 10)));
+    }
+
+    #[test]
+    fn pending_offer_expires_after_ttl() {
+        let range = range(10, 10);
+        let mac_a = [1, 2, 3, 4, 5, 6];
+        let mac_b = [6, 5, 4, 3, 2, 1];
+
+        let mut table = LeaseTable::new();
+        let offered_at = Instant::now();
+        table.offer(mac_a, Ipv4Addr::new(10, 0, 0, 10), offered_at);
+
+        // Still within the TTL, the sole address in the range is unavailable
+        // to a different client.
+        assert_eq!(table.pick_address(&range, &mac_b, None, offered_at), None);
+
+        // Past the TTL the offer is stale and the sweep reclaims it.
+        let later = offered_at + PENDING_OFFER_TTL + Duration::from_secs(1);
+        assert_eq!(table.expire_stale(later), 1);
+        assert_eq!(
+            table.pick_address(&range, &mac_b, None, later),
+            Some(Ipv4Addr::new(10, 0, 0, 10))
+        );
+    }
+}
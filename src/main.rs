@@ -1,13 +1,25 @@
+mod config;
 mod dhcp;
+mod error;
+mod events;
+mod lease;
+mod pxe_menu;
 
 use actix_files as fs;
 use actix_web::{middleware::Logger, App, HttpServer};
 use anyhow::Error;
 use async_tftp::server::TftpServerBuilder;
+use config::Config;
 use dhcp::DHCPProxyBuilder;
-use dhcproto::v4::Architecture;
 use log::{error, info, trace};
-use std::net::Ipv4Addr;
+use std::env;
+
+fn config_path() -> String {
+    env::args()
+        .nth(1)
+        .or_else(|| env::var("RUSTED_PXE_CONFIG").ok())
+        .unwrap_or_else(|| "config.yaml".to_string())
+}
 
 #[actix_web::main]
 async fn main() -> Result<(), Error> {
@@ -18,33 +30,46 @@ async fn main() -> Result<(), Error> {
         .apply()
         .expect("Failed to initialize logger");
 
+    let config = Config::load(config_path())?;
+
     // Setup DHCP Server
+    let dhcp_config = config.clone();
     actix_rt::spawn(async move {
         loop {
             info!("Starting DHCP Server");
-            let server_address = Ipv4Addr::new(10, 0, 0, 5);
 
-            let dhcp_proxy = DHCPProxyBuilder::new()
-                .add_responder(
-                    Some(Architecture::BC),
-                    None,
-                    server_address,
-                    "ipxe.efi".to_string(),
-                )
-                .add_responder(
-                    Some(Architecture::Intelx86PC),
-                    None,
-                    server_address,
-                    "undionly.kpxe".to_string(),
-                )
-                .add_responder(
-                    None,
-                    Some("iPXE".to_string()),
-                    server_address,
-                    "http://10.0.0.5/boot.ipxe".to_string(),
-                )
-                .build()
-                .await;
+            let mut builder = DHCPProxyBuilder::new()
+                .listen_address(dhcp_config.dhcp_listen_address)
+                .server_address(dhcp_config.server_address);
+            if let Some(event_log_file) = &dhcp_config.event_log_file {
+                builder = builder.event_log_file(event_log_file.clone().into());
+            }
+            for responder in &dhcp_config.responders {
+                builder = builder.add_responder(
+                    responder.architecture,
+                    responder.user_class.clone(),
+                    responder.redirect_to,
+                    responder.boot_file.clone(),
+                );
+                if let Some(menu) = &responder.menu {
+                    builder = builder.with_menu(menu.build());
+                }
+            }
+
+            let dhcp_proxy = match dhcp_config
+                .ranges
+                .iter()
+                .map(|range| range.build())
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(ranges) => {
+                    for range in ranges {
+                        builder = builder.add_range(range);
+                    }
+                    builder.build().await
+                }
+                Err(err) => Err(err),
+            };
 
             match dhcp_proxy {
                 Ok(dhcp_proxy) => match dhcp_proxy.run().await {
@@ -67,10 +92,11 @@ async fn main() -> Result<(), Error> {
     });
 
     // Setup TFTP Server
+    let tftp_root = config.tftp_root.clone();
     actix_rt::spawn(async move {
         loop {
             info!("Starting TFTP Server");
-            let tftpd = TftpServerBuilder::with_dir_ro("./tftp_root")
+            let tftpd = TftpServerBuilder::with_dir_ro(&tftp_root)
                 .unwrap()
                 .bind("0.0.0.0:69".parse().unwrap())
                 .block_size_limit(1024)
@@ -97,10 +123,11 @@ async fn main() -> Result<(), Error> {
     });
 
     // Setup HTTP Server
-    HttpServer::new(|| {
+    let http_root = config.http_root.clone();
+    HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
-            .service(fs::Files::new("/", "./http_root"))
+            .service(fs::Files::new("/", &http_root))
     })
     .bind(("0.0.0.0", 80))?
     .run()
@@ -0,0 +1,222 @@
+use log::trace;
+
+/// Bytes per entry in sub-option 8's boot-servers list: a 2-byte server
+/// type, a 1-byte IP count, and one IPv4 address.
+const BOOT_SERVER_ENTRY_LEN: usize = 7;
+
+/// Each PXE sub-option's length is a single byte, so its payload can't
+/// exceed this.
+const MAX_SUBOPTION_LEN: usize = u8::MAX as usize;
+
+/// A single selectable entry in a PXE firmware boot menu (PXE_BOOT_SERVERS /
+/// option 43 sub-option 71 "Boot Item").
+#[derive(Clone, Debug)]
+pub struct PxeBootMenuEntry {
+    server_type: u16,
+    description: String,
+    boot_file: String,
+}
+
+impl PxeBootMenuEntry {
+    pub fn new(
+        server_type: u16,
+        description: impl Into<String>,
+        boot_file: impl Into<String>,
+    ) -> PxeBootMenuEntry {
+        PxeBootMenuEntry {
+            server_type,
+            description: description.into(),
+            boot_file: boot_file.into(),
+        }
+    }
+
+    pub fn boot_file(&self) -> &str {
+        &self.boot_file
+    }
+}
+
+/// An interactive PXE firmware boot menu, encoded into option 43 sub-options
+/// 6/8/9/10 so clients offer "install / rescue / local disk" style prompts
+/// instead of booting a single forced NBP.
+#[derive(Clone, Debug)]
+pub struct PxeBootMenu {
+    entries: Vec<PxeBootMenuEntry>,
+    prompt: Option<String>,
+    timeout_secs: u8,
+}
+
+impl PxeBootMenu {
+    pub fn new(entries: Vec<PxeBootMenuEntry>, prompt: Option<String>, timeout_secs: u8) -> PxeBootMenu {
+        PxeBootMenu {
+            entries,
+            prompt,
+            timeout_secs,
+        }
+    }
+
+    /// The boot file for the entry matching `server_type`, if any (selected
+    /// via option 43 sub-option 71 on the client's follow-up request).
+    pub fn boot_file_for(&self, server_type: u16) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.server_type == server_type)
+            .map(PxeBootMenuEntry::boot_file)
+    }
+
+    fn prompt_text(&self) -> String {
+        self.prompt.clone().unwrap_or_else(|| {
+            self.entries
+                .iter()
+                .map(|entry| entry.description.as_str())
+                .collect::<Vec<_>>()
+                .join(" / ")
+        })
+    }
+
+    /// Encodes this menu's sub-options into a PXE vendor-extensions (option
+    /// 43) payload, terminated with the PXEClient end tag (`255`).
+    pub fn encode(&self, redirect_to: std::net::Ipv4Addr) -> Vec<u8> {
+        let mut options = Vec::new();
+
+        // Sub-option 6: discovery control. 0 leaves broadcast/multicast
+        // discovery enabled; clients still show the menu we provide below.
+        options.push(6);
+        options.push(1);
+        options.push(0);
+
+        // Sub-option 8: boot servers list, one Type(u16)+IpCount(u8)+IPs
+        // entry per menu item, all pointing back at this proxy. Its length
+        // is a single byte, so cap the entry count to what fits.
+        let max_entries = MAX_SUBOPTION_LEN / BOOT_SERVER_ENTRY_LEN;
+        if self.entries.len() > max_entries {
+            trace!(
+                "PXE boot menu has {} entries, truncating to {max_entries} to fit sub-option 8's 1-byte length",
+                self.entries.len()
+            );
+        }
+        let mut boot_servers = Vec::new();
+        for entry in self.entries.iter().take(max_entries) {
+            boot_servers.extend_from_slice(&entry.server_type.to_be_bytes());
+            boot_servers.push(1);
+            boot_servers.extend_from_slice(&redirect_to.octets());
+        }
+        options.push(8);
+        options.push(boot_servers.len() as u8);
+        options.extend(boot_servers);
+
+        // Sub-option 9: the prompt text shown to the operator, truncated at
+        // a char boundary to fit its own 1-byte length.
+        let prompt_text = self.prompt_text();
+        let prompt = truncate_to_byte_len(&prompt_text, MAX_SUBOPTION_LEN);
+        if prompt.len() < prompt_text.len() {
+            trace!(
+                "PXE boot menu prompt is {} bytes, truncating to {} to fit sub-option 9's 1-byte length",
+                prompt_text.len(),
+                prompt.len()
+            );
+        }
+        options.push(9);
+        options.push(prompt.len() as u8);
+        options.extend(prompt.as_bytes());
+
+        // Sub-option 10: how long (seconds) to display the prompt before
+        // falling back to the first entry.
+        options.push(10);
+        options.push(1);
+        options.push(self.timeout_secs);
+
+        options.push(255);
+        options
+    }
+}
+
+/// Parses a PXE vendor-extensions (option 43) payload received from a client
+/// and returns the boot server type selected via sub-option 71, if present.
+pub fn selected_boot_item(vendor_extensions: &[u8]) -> Option<u16> {
+    let mut i = 0;
+    while i < vendor_extensions.len() {
+        let tag = vendor_extensions[i];
+        if tag == 255 {
+            break;
+        }
+        if tag == 0 {
+            i += 1;
+            continue;
+        }
+        let len = *vendor_extensions.get(i + 1)? as usize;
+        let start = i + 2;
+        let end = start.checked_add(len)?;
+        let value = vendor_extensions.get(start..end)?;
+
+        if tag == 71 && value.len() >= 2 {
+            return Some(u16::from_be_bytes([value[0], value[1]]));
+        }
+
+        i = end;
+    }
+    None
+}
+
+/// Truncates `s` to at most `max` bytes, stepping back to the nearest char
+/// boundary so we never split a multi-byte UTF-8 sequence.
+fn truncate_to_byte_len(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn entry(server_type: u16) -> PxeBootMenuEntry {
+        PxeBootMenuEntry::new(server_type, format!("item {server_type}"), "boot.efi")
+    }
+
+    #[test]
+    fn selected_boot_item_maps_to_menu_entry() {
+        let menu = PxeBootMenu::new(vec![entry(0), entry(7)], Some("pick one".to_string()), 10);
+        let encoded = menu.encode(Ipv4Addr::new(10, 0, 0, 5));
+
+        // A client replies with its pick via sub-option 71.
+        let mut reply = Vec::new();
+        reply.push(71);
+        reply.push(2);
+        reply.extend_from_slice(&7u16.to_be_bytes());
+        reply.push(255);
+
+        assert_eq!(selected_boot_item(&reply), Some(7));
+        assert_eq!(menu.boot_file_for(7), Some("boot.efi"));
+        assert!(encoded.ends_with(&[255]));
+    }
+
+    #[test]
+    fn encode_caps_boot_servers_suboption_at_one_byte_of_length() {
+        // 100 entries * 7 bytes/entry would overflow a u8 length if uncapped.
+        let entries: Vec<_> = (0..100).map(entry).collect();
+        let menu = PxeBootMenu::new(entries, None, 10);
+        let encoded = menu.encode(Ipv4Addr::new(10, 0, 0, 5));
+
+        // Sub-option 6 is always [6, 1, 0], so sub-option 8 starts right after.
+        assert_eq!(&encoded[0..3], &[6, 1, 0]);
+        let suboption_8_len = encoded[4] as usize;
+        assert!(suboption_8_len <= u8::MAX as usize);
+        assert_eq!(suboption_8_len, (u8::MAX as usize / BOOT_SERVER_ENTRY_LEN) * BOOT_SERVER_ENTRY_LEN);
+        // And the declared length must match what was actually appended.
+        let suboption_9_tag_index = 5 + suboption_8_len;
+        assert_eq!(encoded[suboption_9_tag_index], 9);
+    }
+
+    #[test]
+    fn truncate_to_byte_len_respects_char_boundaries() {
+        let s = "a€b"; // '€' is 3 bytes, so byte index 2 lands mid-character
+        assert_eq!(truncate_to_byte_len(s, 2), "a");
+        assert_eq!(truncate_to_byte_len(s, 100), s);
+    }
+}